@@ -1,11 +1,17 @@
-use crate::{error::*, output::*};
+use crate::{
+    error::*,
+    filter::{entry_path, EntryFilter},
+    output::*,
+};
 use byteorder::*;
 use encoding::{codec::japanese::Windows31JEncoding, DecoderTrap, Encoding};
 use libflate::zlib::Decoder as ZlibDecoder;
 use std::{
     fs::File,
-    io::{BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
     str::from_utf8,
+    thread,
 };
 
 #[allow(non_camel_case_types)]
@@ -40,9 +46,14 @@ pub fn determine_archive_type(mut read: impl Read + Seek) -> ArchiveType {
     }
 }
 
-fn transfer(out: &mut Output, dir: &str, name: &str, mut read: impl Read, size: u64) -> Result<()> {
-    let mut write = out.create(dir, name)?;
+fn transfer(
+    out: &mut impl ArchiveSink, dir: &str, name: &str, read: impl Read, size: u64,
+) -> Result<()> {
+    let write = out.create(dir, name)?;
+    copy_entry(read, write, name, size)
+}
 
+fn copy_entry(mut read: impl Read, mut write: impl Write, name: &str, size: u64) -> Result<()> {
     let mut buffer = [0u8; 1024 * 64];
     let mut remaining = size;
     while remaining > 0 {
@@ -108,7 +119,64 @@ struct FileEntry {
     offset: u64,
     len: u64,
 }
-pub fn extract_012m(file: File, out: &mut Output) -> Result<()> {
+
+pub struct EntryInfo {
+    pub dir: String,
+    pub name: String,
+    pub compressed_len: u64,
+    pub uncompressed_len: u64,
+    pub offset: u64,
+    pub is_compressed: bool,
+}
+
+pub fn list_012m(mut file: impl Read + Seek) -> Result<Vec<EntryInfo>> {
+    assert!(check_archive_header(&mut file, ARCHIVE_012M_MAGIC)?);
+    let file_count = file.read_u32::<LE>()?;
+
+    let mut raw_entries = Vec::new();
+    for _ in 0..file_count {
+        let name = read_cstr(&mut file)?;
+        let offset = file.read_u32::<LE>()? as u64;
+        let len = file.read_u32::<LE>()? as u64;
+        raw_entries.push(FileEntry { name, offset, len })
+    }
+
+    let mut entries = Vec::with_capacity(raw_entries.len());
+    for FileEntry { name, offset, len } in raw_entries {
+        file.seek(SeekFrom::Start(offset))?;
+        // An entry shorter than the magic plus its trailing u32 length can never actually be
+        // a compressed entry; treating it as one would both peek past its end into the next
+        // entry and underflow the `len - 4 - MAGIC.len()` subtraction below.
+        let is_compressed = len >= COMPRESS_ZIP_MAGIC.len() as u64 + 4
+            && check_archive_header_no_seek(&mut file, COMPRESS_ZIP_MAGIC).unwrap_or(false);
+        if is_compressed {
+            let uncompressed_len = file.read_u32::<LE>()? as u64;
+            let compressed_len = len - 4 - COMPRESS_ZIP_MAGIC.len() as u64;
+            entries.push(EntryInfo {
+                dir: String::new(),
+                name,
+                compressed_len,
+                uncompressed_len,
+                offset,
+                is_compressed: true,
+            });
+        } else {
+            entries.push(EntryInfo {
+                dir: String::new(),
+                name,
+                compressed_len: len,
+                uncompressed_len: len,
+                offset,
+                is_compressed: false,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+pub fn extract_012m(
+    file: impl Read + Seek, out: &mut impl ArchiveSink, filter: Option<&EntryFilter>,
+) -> Result<()> {
     let mut file = BufReader::new(file);
 
     assert!(check_archive_header(&mut file, ARCHIVE_012M_MAGIC)?);
@@ -122,8 +190,18 @@ pub fn extract_012m(file: File, out: &mut Output) -> Result<()> {
         entries.push(FileEntry { name, offset, len })
     }
     for FileEntry { name, offset, len } in entries {
+        if let Some(filter) = filter {
+            if !filter.matches(&entry_path("", &name)) {
+                continue;
+            }
+        }
+
         file.seek(SeekFrom::Start(offset))?;
-        if check_archive_header_no_seek(&mut file, COMPRESS_ZIP_MAGIC).unwrap_or(false) {
+        // See the matching guard in `list_012m`: an entry shorter than the magic plus its
+        // trailing u32 length can never actually be compressed.
+        let is_compressed = len >= COMPRESS_ZIP_MAGIC.len() as u64 + 4
+            && check_archive_header_no_seek(&mut file, COMPRESS_ZIP_MAGIC).unwrap_or(false);
+        if is_compressed {
             let uncompressed_len = file.read_u32::<LE>()? as u64;
             let compressed_len = len - 4 - COMPRESS_ZIP_MAGIC.len() as u64;
             let in_stream = (&mut file).take(compressed_len);
@@ -157,7 +235,8 @@ fn extract_ph3_inner(
     mut header_stream: impl Read,
     mut contents_stream: impl Read + Seek,
     file_count: u32,
-    out: &mut Output,
+    out: &mut impl ArchiveSink,
+    filter: Option<&EntryFilter>,
 ) -> Result<()> {
     for _ in 0..file_count {
         let _entry_len = header_stream.read_u32::<LE>()? as u64;
@@ -168,6 +247,12 @@ fn extract_ph3_inner(
         let compressed_len = header_stream.read_u32::<LE>()? as u64;
         let offset = header_stream.read_u32::<LE>()? as u64;
 
+        if let Some(filter) = filter {
+            if !filter.matches(&entry_path(&dir_name, &entry_name)) {
+                continue;
+            }
+        }
+
         contents_stream.seek(SeekFrom::Start(offset))?;
         if is_compressed {
             let in_stream = (&mut contents_stream).take(compressed_len);
@@ -179,7 +264,41 @@ fn extract_ph3_inner(
     }
     Ok(())
 }
-pub fn extract_ph3(file: File, out: &mut Output) -> Result<()> {
+pub fn extract_ph3(
+    file: impl Read + Seek, out: &mut impl ArchiveSink, filter: Option<&EntryFilter>,
+) -> Result<()> {
+    let mut file = BufReader::new(file);
+
+    assert!(check_archive_header(&mut file, ARCHIVE_PH3_MAGIC)?);
+    let file_count = file.read_u32::<LE>()?;
+    let is_compressed = file.read_u8()? != 0;
+    let header_size = file.read_u32::<LE>()? as u64;
+    assert!(header_size <= usize::max_value() as u64);
+    let mut header = vec![0u8; header_size as usize];
+    file.read_exact(&mut header)?;
+
+    if is_compressed {
+        extract_ph3_inner(ZlibDecoder::new(Cursor::new(header))?, file, file_count, out, filter)
+    } else {
+        extract_ph3_inner(Cursor::new(header), file, file_count, out, filter)
+    }
+}
+
+fn list_ph3_inner(mut header_stream: impl Read, file_count: u32) -> Result<Vec<EntryInfo>> {
+    let mut entries = Vec::with_capacity(file_count as usize);
+    for _ in 0..file_count {
+        let _entry_len = header_stream.read_u32::<LE>()? as u64;
+        let dir = read_wchar_str(&mut header_stream)?;
+        let name = read_wchar_str(&mut header_stream)?;
+        let is_compressed = header_stream.read_u32::<LE>()? != 0;
+        let uncompressed_len = header_stream.read_u32::<LE>()? as u64;
+        let compressed_len = header_stream.read_u32::<LE>()? as u64;
+        let offset = header_stream.read_u32::<LE>()? as u64;
+        entries.push(EntryInfo { dir, name, compressed_len, uncompressed_len, offset, is_compressed });
+    }
+    Ok(entries)
+}
+pub fn list_ph3(file: impl Read + Seek) -> Result<Vec<EntryInfo>> {
     let mut file = BufReader::new(file);
 
     assert!(check_archive_header(&mut file, ARCHIVE_PH3_MAGIC)?);
@@ -191,8 +310,106 @@ pub fn extract_ph3(file: File, out: &mut Output) -> Result<()> {
     file.read_exact(&mut header)?;
 
     if is_compressed {
-        extract_ph3_inner(ZlibDecoder::new(Cursor::new(header))?, file, file_count, out)
+        list_ph3_inner(ZlibDecoder::new(Cursor::new(header))?, file_count)
+    } else {
+        list_ph3_inner(Cursor::new(header), file_count)
+    }
+}
+
+// Parallel extraction
+///////////////////////
+
+// Every entry's offset and length is known up front from the header tables parsed by
+// `list_012m`/`list_ph3`, so entries can be read and decompressed concurrently. Each worker
+// opens its own handle to the archive and seeks independently; output paths are resolved
+// (and deduplicated) on the calling thread before any worker starts, since `OutputDirNode`'s
+// duplicate-name resolution is order-sensitive. All dedup WARNING messages are therefore
+// produced in entry order before dispatch, so their relative order matches single-threaded
+// extraction. Entries are then written concurrently, so the WARNING messages `copy_entry`
+// prints for truncated or overlong entry data appear in nondeterministic order.
+fn content_offset_012m(entry: &EntryInfo) -> u64 {
+    if entry.is_compressed {
+        entry.offset + COMPRESS_ZIP_MAGIC.len() as u64 + 4
     } else {
-        extract_ph3_inner(Cursor::new(header), file, file_count, out)
+        entry.offset
     }
 }
+
+fn extract_entry_parallel(
+    file: &mut File, content_offset: u64, entry: &EntryInfo, out_path: &Path,
+) -> Result<()> {
+    file.seek(SeekFrom::Start(content_offset))?;
+    let write = BufWriter::new(File::create(out_path)?);
+    if entry.is_compressed {
+        let in_stream = (&mut *file).take(entry.compressed_len);
+        copy_entry(ZlibDecoder::new(in_stream)?, write, &entry.name, entry.uncompressed_len)
+    } else {
+        let in_stream = (&mut *file).take(entry.uncompressed_len);
+        copy_entry(in_stream, write, &entry.name, entry.uncompressed_len)
+    }
+}
+
+fn run_parallel<F>(
+    path: &Path, entries: &[EntryInfo], paths: &[PathBuf], jobs: usize, content_offset: F,
+) -> Result<()>
+where
+    F: Fn(&EntryInfo) -> u64 + Sync,
+{
+    let jobs = jobs.max(1);
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); jobs];
+    for i in 0..entries.len() {
+        buckets[i % jobs].push(i);
+    }
+
+    thread::scope(|scope| -> Result<()> {
+        let mut handles = Vec::new();
+        for bucket in buckets {
+            if bucket.is_empty() {
+                continue;
+            }
+            let content_offset = &content_offset;
+            handles.push(scope.spawn(move || -> Result<()> {
+                let mut file = File::open(path)?;
+                for i in bucket {
+                    let entry = &entries[i];
+                    extract_entry_parallel(&mut file, content_offset(entry), entry, &paths[i])?;
+                }
+                Ok(())
+            }));
+        }
+        for handle in handles {
+            handle.join().expect("extraction worker thread panicked")?;
+        }
+        Ok(())
+    })
+}
+
+fn filter_entries(entries: Vec<EntryInfo>, filter: Option<&EntryFilter>) -> Vec<EntryInfo> {
+    match filter {
+        Some(filter) => entries
+            .into_iter()
+            .filter(|entry| filter.matches(&entry_path(&entry.dir, &entry.name)))
+            .collect(),
+        None => entries,
+    }
+}
+
+pub fn extract_012m_parallel(
+    path: &Path, out: &mut Output, jobs: usize, filter: Option<&EntryFilter>,
+) -> Result<()> {
+    let entries = filter_entries(list_012m(File::open(path)?)?, filter);
+    let names: Vec<(String, String)> =
+        entries.iter().map(|e| (e.dir.clone(), e.name.clone())).collect();
+    let paths = out.resolve_paths(&names)?;
+    run_parallel(path, &entries, &paths, jobs, content_offset_012m)
+}
+
+pub fn extract_ph3_parallel(
+    path: &Path, out: &mut Output, jobs: usize, filter: Option<&EntryFilter>,
+) -> Result<()> {
+    let entries = filter_entries(list_ph3(File::open(path)?)?, filter);
+    let names: Vec<(String, String)> =
+        entries.iter().map(|e| (e.dir.clone(), e.name.clone())).collect();
+    let paths = out.resolve_paths(&names)?;
+    run_parallel(path, &entries, &paths, jobs, |entry| entry.offset)
+}
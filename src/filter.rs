@@ -0,0 +1,60 @@
+// Simple shell-style glob matching (`*` and `?`) against an entry's full `dir/name` path,
+// in the spirit of pxar's `match_pattern`.
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => glob_match(&pattern[1..], text) || (!text.is_empty() && glob_match(pattern, &text[1..])),
+        Some('?') => !text.is_empty() && glob_match(&pattern[1..], &text[1..]),
+        Some(&c) => match text.first() {
+            Some(&t) if t == c => glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+    }
+}
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match(&pattern, &text)
+}
+
+/// Selects which archive entries to extract based on `--include`/`--exclude` glob patterns,
+/// matched against an entry's full `dir/name` path.
+#[derive(Default)]
+pub struct EntryFilter {
+    includes: Vec<String>,
+    excludes: Vec<String>,
+}
+impl EntryFilter {
+    pub fn new() -> EntryFilter {
+        EntryFilter::default()
+    }
+
+    pub fn add_include(&mut self, pattern: impl Into<String>) {
+        self.includes.push(pattern.into());
+    }
+    pub fn add_exclude(&mut self, pattern: impl Into<String>) {
+        self.excludes.push(pattern.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.includes.is_empty() && self.excludes.is_empty()
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        if !self.includes.is_empty() && !self.includes.iter().any(|p| matches_pattern(p, path)) {
+            return false;
+        }
+        if self.excludes.iter().any(|p| matches_pattern(p, path)) {
+            return false;
+        }
+        true
+    }
+}
+
+pub fn entry_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
@@ -0,0 +1,17 @@
+extern crate byteorder;
+extern crate encoding;
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate lazy_static;
+extern crate libflate;
+
+pub mod error {
+    pub use failure::Error;
+    pub type Result<T> = ::std::result::Result<T, Error>;
+}
+
+pub mod archive;
+pub mod filter;
+pub mod output;
+pub mod pack;
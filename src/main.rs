@@ -1,20 +1,10 @@
 #![windows_subsystem = "console"]
 
-extern crate byteorder;
-extern crate encoding;
+extern crate th_dnh_archiver;
 #[macro_use]
 extern crate failure;
-#[macro_use]
-extern crate lazy_static;
-extern crate libflate;
-
-mod error {
-    pub use failure::Error;
-    pub type Result<T> = ::std::result::Result<T, Error>;
-}
 
-mod archive;
-mod output;
+use th_dnh_archiver::{archive, error, filter::EntryFilter, output, output::ArchiveSink, pack};
 
 // Main method
 //////////////
@@ -23,7 +13,7 @@ use std::{
     env,
     ffi::OsString,
     fs::File,
-    io::stdin,
+    io::{stdin, BufWriter},
     mem,
     panic::{catch_unwind, AssertUnwindSafe},
     path::PathBuf,
@@ -31,7 +21,7 @@ use std::{
     time::Instant,
 };
 
-fn extract(path: PathBuf) -> error::Result<()> {
+fn extract(path: PathBuf, jobs: Option<usize>, filter: EntryFilter) -> error::Result<()> {
     let mut file = File::open(&path)?;
     let arc_type = archive::determine_archive_type(&mut file);
     if let archive::ArchiveType::NotAnArchive = arc_type {
@@ -39,14 +29,22 @@ fn extract(path: PathBuf) -> error::Result<()> {
         return Ok(());
     }
 
+    let filter = if filter.is_empty() { None } else { Some(&filter) };
+
     let mut output = output::Output::for_path(&path)?;
     println!("Extracting '{}' to '{}'...", path.display(), output.display_out_path());
     let start_time = Instant::now();
 
-    match arc_type {
-        archive::ArchiveType::Archive_Ph3 => archive::extract_ph3(file, &mut output)?,
-        archive::ArchiveType::Archive_012M => archive::extract_012m(file, &mut output)?,
-        _ => unreachable!(),
+    match (arc_type, jobs) {
+        (archive::ArchiveType::Archive_Ph3, Some(jobs)) => {
+            archive::extract_ph3_parallel(&path, &mut output, jobs, filter)?
+        }
+        (archive::ArchiveType::Archive_012M, Some(jobs)) => {
+            archive::extract_012m_parallel(&path, &mut output, jobs, filter)?
+        }
+        (archive::ArchiveType::Archive_Ph3, None) => archive::extract_ph3(file, &mut output, filter)?,
+        (archive::ArchiveType::Archive_012M, None) => archive::extract_012m(file, &mut output, filter)?,
+        (archive::ArchiveType::NotAnArchive, _) => unreachable!(),
     }
 
     let total_time = Instant::now().duration_since(start_time);
@@ -58,6 +56,84 @@ fn extract(path: PathBuf) -> error::Result<()> {
     Ok(())
 }
 
+fn create(path: PathBuf) -> error::Result<()> {
+    let base_name = match path.file_name() {
+        Some(name) => name.to_owned(),
+        None => bail!("Could not get directory name for '{}'", path.display()),
+    };
+
+    let mut suffix_count = 1;
+    let mut out_path = path.clone();
+    loop {
+        let file_name = if suffix_count == 1 {
+            format!("{}.dat", base_name.to_string_lossy())
+        } else {
+            format!("{}_{}.dat", base_name.to_string_lossy(), suffix_count)
+        };
+        out_path.set_file_name(&file_name);
+        if !out_path.exists() {
+            break;
+        }
+        suffix_count += 1;
+    }
+
+    println!("Creating '{}' from '{}'...", out_path.display(), path.display());
+    let start_time = Instant::now();
+
+    let file = File::create(&out_path)?;
+    pack::create_ph3(&path, BufWriter::new(file))?;
+
+    let total_time = Instant::now().duration_since(start_time);
+    println!(
+        "Created archive in {} ms.",
+        total_time.as_secs() * 1000 + total_time.subsec_millis() as u64
+    );
+    Ok(())
+}
+
+fn list(path: PathBuf) -> error::Result<()> {
+    let mut file = File::open(&path)?;
+    let arc_type = archive::determine_archive_type(&mut file);
+    let entries = match arc_type {
+        archive::ArchiveType::Archive_012M => archive::list_012m(file)?,
+        archive::ArchiveType::Archive_Ph3 => archive::list_ph3(file)?,
+        archive::ArchiveType::NotAnArchive => {
+            eprintln!("File '{}' is not a Danmakufu 0.12m or ph3 archive.", path.display());
+            return Ok(());
+        }
+    };
+
+    println!("{:>12} {:>12} {:>7}  {}", "Compressed", "Uncompressed", "Ratio", "Name");
+    let mut total_compressed = 0u64;
+    let mut total_uncompressed = 0u64;
+    for entry in &entries {
+        let full_name =
+            if entry.dir.is_empty() { entry.name.clone() } else { format!("{}/{}", entry.dir, entry.name) };
+        let ratio = if entry.uncompressed_len == 0 {
+            100.0
+        } else {
+            entry.compressed_len as f64 / entry.uncompressed_len as f64 * 100.0
+        };
+        println!("{:>12} {:>12} {:>6.1}%  {}", entry.compressed_len, entry.uncompressed_len, ratio, full_name);
+        total_compressed += entry.compressed_len;
+        total_uncompressed += entry.uncompressed_len;
+    }
+    let total_ratio = if total_uncompressed == 0 {
+        100.0
+    } else {
+        total_compressed as f64 / total_uncompressed as f64 * 100.0
+    };
+    println!(
+        "{:>12} {:>12} {:>6.1}%  {} files",
+        total_compressed,
+        total_uncompressed,
+        total_ratio,
+        entries.len()
+    );
+
+    Ok(())
+}
+
 fn press_any_key() {
     eprint!("Press Enter to continue... ");
     stdin().read_line(&mut String::new()).unwrap();
@@ -71,31 +147,83 @@ fn main() {
     env::set_var("RUST_BACKTRACE", "1");
 
     let mut args: Vec<OsString> = env::args_os().collect();
-    if args.len() != 2 {
-        eprintln!("To extract a .dat file, drag it onto {}.", args[0].to_string_lossy());
-        eprintln!(
-            "To create a .dat file, drag a single directory onto {}.",
-            args[0].to_string_lossy()
-        );
+    if args.len() == 3 && args[1] == "list" {
+        let path = PathBuf::from(args.pop().unwrap());
+        if !path.is_file() {
+            eprintln!("'{}' is not a regular file.", path.display());
+            after_error();
+        }
+        match catch_unwind(AssertUnwindSafe(|| list(path))) {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                eprintln!("Error: {}\n{}", err, err.backtrace());
+                after_error();
+            }
+            Err(_) => after_error(),
+        }
+        return;
+    }
+    let prog_name = args[0].to_string_lossy().into_owned();
+    let mut jobs = None;
+    let mut filter = EntryFilter::new();
+    let mut positional = Vec::new();
+    let mut rest = args.drain(1..);
+    while let Some(arg) = rest.next() {
+        if arg == "--jobs" {
+            let value = rest.next().unwrap_or_else(|| {
+                eprintln!("'--jobs' requires a number of worker threads.");
+                after_error();
+                unreachable!()
+            });
+            jobs = Some(value.to_string_lossy().parse::<usize>().unwrap_or_else(|_| {
+                eprintln!("'--jobs' value '{}' is not a valid number.", value.to_string_lossy());
+                after_error();
+                unreachable!()
+            }));
+        } else if arg == "--include" || arg == "--exclude" {
+            let value = rest.next().unwrap_or_else(|| {
+                eprintln!("'{}' requires a glob pattern.", arg.to_string_lossy());
+                after_error();
+                unreachable!()
+            });
+            if arg == "--include" {
+                filter.add_include(value.to_string_lossy().into_owned());
+            } else {
+                filter.add_exclude(value.to_string_lossy().into_owned());
+            }
+        } else {
+            positional.push(arg);
+        }
+    }
+    mem::drop(rest);
+    mem::drop(args);
+
+    if positional.len() != 1 {
+        eprintln!("To extract a .dat file, drag it onto {}.", prog_name);
+        eprintln!("To create a .dat file, drag a single directory onto {}.", prog_name);
         eprintln!(
             "Alternatively, if you are using a terminal, please use: \
-                   {} [archive to extract]",
-            args[0].to_string_lossy()
+                   {0} [--jobs N] [--include <glob>]... [--exclude <glob>]... \
+                   [archive to extract], or {0} list [archive to inspect]",
+            prog_name
         );
         after_error();
     }
-    let target_file = args.pop().unwrap();
-    mem::drop(args);
-    let path = PathBuf::from(target_file);
+    let path = PathBuf::from(positional.pop().unwrap());
     if !path.exists() {
         eprintln!("No such file '{}' exists.", path.display());
         after_error();
     }
-    if !path.is_file() {
-        eprintln!("'{}' is not a regular file.", path.display());
+    let result = if path.is_dir() {
+        catch_unwind(AssertUnwindSafe(|| create(path)))
+    } else if path.is_file() {
+        catch_unwind(AssertUnwindSafe(|| extract(path, jobs, filter)))
+    } else {
+        eprintln!("'{}' is not a regular file or directory.", path.display());
         after_error();
-    }
-    match catch_unwind(AssertUnwindSafe(|| extract(path))) {
+        unreachable!()
+    };
+    match result {
         Ok(Ok(())) | Err(_) => press_any_key(),
         Ok(Err(err)) => {
             eprintln!("Error: {}\n{}", err, err.backtrace());
@@ -3,9 +3,19 @@ use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
 use std::fmt::{Display, Write as FmtWrite};
 use std::fs::{File, create_dir_all};
+use std::io;
 use std::io::{Write, BufWriter};
+use std::mem;
 use std::path::{Path, PathBuf};
 
+/// A destination that extracted archive entries are written to. The filesystem-backed
+/// implementation is [`Output`]; other implementations (e.g. an in-memory map) let the
+/// extraction logic in `archive` be used as a library without touching disk.
+pub trait ArchiveSink {
+    fn create(&mut self, dir: &str, name: &str) -> Result<Box<dyn Write + '_>>;
+    fn write_count(&self) -> usize;
+}
+
 pub fn validate_filename(name: &str) -> String {
     let mut new_name = String::new();
     for char in name.trim().trim_right_matches('.').chars() {
@@ -99,24 +109,22 @@ impl OutputDirNode {
     fn write_dir(
         &mut self, original_path: &str, path_name: &mut String, original_name: &str,
     ) -> Result<&mut OutputDirNode> {
-        if let Some(node) = self.subdirs.get_mut(original_name) {
-            Ok(node)
-        } else {
+        if !self.subdirs.contains_key(original_name) {
             let mut path = self.base_path.clone();
             path.push(self.uniq_name(original_path, path_name.as_ref(), original_name)?);
             create_dir_all(&path)?;
 
             self.subdirs.insert(original_name.to_string(), OutputDirNode::new(path));
-            Ok(self.subdirs.get_mut(original_name).unwrap())
         }
+        Ok(self.subdirs.get_mut(original_name).unwrap())
     }
 
-    fn write_file(
+    fn resolve_file(
         &mut self, original_path: &str, path_name: &str, original_name: &str,
-    ) -> Result<impl Write> {
+    ) -> Result<PathBuf> {
         let mut path = self.base_path.clone();
         path.push(self.uniq_name(original_path, path_name, original_name)?);
-        Ok(BufWriter::new(File::create(path)?))
+        Ok(path)
     }
 }
 
@@ -158,11 +166,8 @@ impl Output {
     pub fn display_out_path<'a>(&'a self) -> impl Display + 'a {
         self.root.base_path.display()
     }
-    pub fn write_count(&self) -> usize {
-        self.written_files
-    }
 
-    pub fn create(&mut self, dir: &str, name: &str) -> Result<impl Write> {
+    fn resolve_file_path(&mut self, dir: &str, name: &str) -> Result<PathBuf> {
         let split = normalize_path(dir);
 
         let mut original_path = String::new();
@@ -176,8 +181,87 @@ impl Output {
         for component in &split {
             node = node.write_dir(&original_path, &mut path_name, component)?;
         }
-        let out = node.write_file(&original_path, &path_name, name)?;
+        node.resolve_file(&original_path, &path_name, name)
+    }
+
+    /// Resolves (and deduplicates) the output path for each `(dir, name)` pair up front,
+    /// without opening any files. Used by parallel extraction, where duplicate-name
+    /// resolution must happen on a single thread before work is handed out to workers.
+    pub fn resolve_paths(&mut self, entries: &[(String, String)]) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::with_capacity(entries.len());
+        for (dir, name) in entries {
+            paths.push(self.resolve_file_path(dir, name)?);
+        }
+        self.written_files += entries.len();
+        Ok(paths)
+    }
+}
+impl ArchiveSink for Output {
+    fn create(&mut self, dir: &str, name: &str) -> Result<Box<dyn Write + '_>> {
+        let path = self.resolve_file_path(dir, name)?;
         self.written_files += 1;
-        Ok(out)
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+    fn write_count(&self) -> usize {
+        self.written_files
+    }
+}
+
+/// An [`ArchiveSink`] that keeps extracted entries in memory, keyed by their `dir/name` path,
+/// instead of writing them to disk.
+#[derive(Default)]
+pub struct MemorySink {
+    files: HashMap<String, Vec<u8>>,
+    written_files: usize,
+}
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    pub fn files(&self) -> &HashMap<String, Vec<u8>> {
+        &self.files
+    }
+    pub fn into_files(self) -> HashMap<String, Vec<u8>> {
+        self.files
+    }
+}
+impl ArchiveSink for MemorySink {
+    fn create(&mut self, dir: &str, name: &str) -> Result<Box<dyn Write + '_>> {
+        let split = normalize_path(dir);
+
+        let mut key = String::new();
+        for component in &split {
+            write!(key, "{}/", component)?;
+        }
+        key.push_str(name);
+
+        self.written_files += 1;
+        Ok(Box::new(MemoryEntryWriter { sink: &mut self.files, key, buffer: Vec::new() }))
+    }
+    fn write_count(&self) -> usize {
+        self.written_files
+    }
+}
+
+struct MemoryEntryWriter<'a> {
+    sink: &'a mut HashMap<String, Vec<u8>>,
+    key: String,
+    buffer: Vec<u8>,
+}
+impl<'a> Write for MemoryEntryWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl<'a> Drop for MemoryEntryWriter<'a> {
+    fn drop(&mut self) {
+        let key = mem::take(&mut self.key);
+        let buffer = mem::take(&mut self.buffer);
+        self.sink.insert(key, buffer);
     }
 }
\ No newline at end of file
@@ -0,0 +1,291 @@
+use crate::error::*;
+use byteorder::*;
+use encoding::{codec::japanese::Windows31JEncoding, EncoderTrap, Encoding};
+use libflate::zlib::Encoder as ZlibEncoder;
+use std::{
+    fs::{read_dir, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+const ARCHIVE_012M_MAGIC: &[u8] = b"PACK_FILE\0";
+const ARCHIVE_PH3_MAGIC: &[u8] = b"ArchiveFile";
+const COMPRESS_ZIP_MAGIC: &[u8] = b"COMPRESS_ZIP\0";
+
+fn walk_dir(base: &Path, current: &Path, out: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    let mut entries: Vec<_> = read_dir(current)?.collect::<::std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            walk_dir(base, &path, out)?;
+        } else if path.is_file() {
+            let rel = path.strip_prefix(base).unwrap();
+            let rel_str = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/");
+            out.push((rel_str, path));
+        }
+    }
+    Ok(())
+}
+fn collect_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut out = Vec::new();
+    walk_dir(dir, dir, &mut out)?;
+    Ok(out)
+}
+fn split_rel_path(rel: &str) -> (String, String) {
+    match rel.rfind('/') {
+        Some(idx) => (rel[..idx].to_string(), rel[idx + 1..].to_string()),
+        None => (String::new(), rel.to_string()),
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn zlib_compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    let mut encoder = ZlibEncoder::new(&mut compressed)?;
+    encoder.write_all(data)?;
+    encoder.finish().into_result()?;
+    Ok(compressed)
+}
+
+// Compresses `data` with zlib, returning the compressed bytes only if doing so is actually
+// smaller than storing it raw.
+fn try_compress(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let compressed = zlib_compress(data)?;
+    if compressed.len() < data.len() {
+        Ok(Some(compressed))
+    } else {
+        Ok(None)
+    }
+}
+
+fn wrap_compressed(uncompressed_len: usize, compressed: &[u8]) -> Result<Vec<u8>> {
+    let mut wrapped = Vec::with_capacity(COMPRESS_ZIP_MAGIC.len() + 4 + compressed.len());
+    wrapped.extend_from_slice(COMPRESS_ZIP_MAGIC);
+    wrapped.write_u32::<LE>(uncompressed_len as u32)?;
+    wrapped.extend_from_slice(compressed);
+    Ok(wrapped)
+}
+fn encode_sjis_name(name: &str) -> Vec<u8> {
+    match Windows31JEncoding.encode(name, EncoderTrap::Strict) {
+        Ok(bytes) => bytes,
+        Err(_) => name.as_bytes().to_vec(),
+    }
+}
+fn write_wchar_str(out: &mut Vec<u8>, value: &str) -> Result<()> {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    out.write_u32::<LE>(units.len() as u32)?;
+    for unit in units {
+        out.write_u16::<LE>(unit)?;
+    }
+    Ok(())
+}
+
+struct Entry012m {
+    name: String,
+    data: Vec<u8>,
+}
+
+/// Builds a `PACK_FILE\0` (0.12m) archive, mirroring how `extract_012m` reads one.
+pub struct Builder012m<W: Write> {
+    writer: W,
+    entries: Vec<Entry012m>,
+}
+impl<W: Write> Builder012m<W> {
+    pub fn new(writer: W) -> Builder012m<W> {
+        Builder012m { writer, entries: Vec::new() }
+    }
+
+    pub fn append_file(&mut self, name: impl Into<String>, path: impl AsRef<Path>) -> Result<()> {
+        let data = read_file(path.as_ref())?;
+        self.entries.push(Entry012m { name: name.into(), data });
+        Ok(())
+    }
+    // 0.12m is a flat namespace with no directory field, unlike ph3's `dir_name`, so a
+    // nested relative path is flattened to its file name, which may collide with an entry
+    // from a different directory.
+    pub fn append_dir_all(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        for (rel, path) in collect_files(dir.as_ref())? {
+            let name = match rel.rfind('/') {
+                Some(idx) => {
+                    let name = rel[idx + 1..].to_string();
+                    eprintln!(
+                        "WARNING: 0.12m archives have no directory field; flattening '{}' to '{}'.",
+                        rel, name
+                    );
+                    name
+                }
+                None => rel,
+            };
+            self.append_file(name, path)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        struct Prepared {
+            name_bytes: Vec<u8>,
+            body: Vec<u8>,
+        }
+
+        // A body that would be stored raw but is shorter than `COMPRESS_ZIP_MAGIC` plus its
+        // trailing u32 length is always stored compressed instead, even if that grows the
+        // entry. `list_012m`/`extract_012m` only look for the magic in entries at least that
+        // long, so they read such a short raw body correctly either way.
+        let min_raw_len = COMPRESS_ZIP_MAGIC.len() + 4;
+
+        let mut prepared = Vec::with_capacity(self.entries.len());
+        for entry in self.entries.drain(..) {
+            let name_bytes = encode_sjis_name(&entry.name);
+            let body = match try_compress(&entry.data)? {
+                Some(compressed) => wrap_compressed(entry.data.len(), &compressed)?,
+                None if entry.data.len() < min_raw_len => {
+                    wrap_compressed(entry.data.len(), &zlib_compress(&entry.data)?)?
+                }
+                None => entry.data,
+            };
+            prepared.push(Prepared { name_bytes, body });
+        }
+
+        let mut header_len = ARCHIVE_012M_MAGIC.len() as u64 + 4;
+        for entry in &prepared {
+            header_len += 4 + entry.name_bytes.len() as u64 + 4 + 4;
+        }
+
+        self.writer.write_all(ARCHIVE_012M_MAGIC)?;
+        self.writer.write_u32::<LE>(prepared.len() as u32)?;
+
+        let mut offset = header_len;
+        for entry in &prepared {
+            self.writer.write_u32::<LE>(entry.name_bytes.len() as u32)?;
+            self.writer.write_all(&entry.name_bytes)?;
+            self.writer.write_u32::<LE>(offset as u32)?;
+            self.writer.write_u32::<LE>(entry.body.len() as u32)?;
+            offset += entry.body.len() as u64;
+        }
+        for entry in &prepared {
+            self.writer.write_all(&entry.body)?;
+        }
+
+        Ok(self.writer)
+    }
+}
+
+struct EntryPh3 {
+    dir_name: String,
+    entry_name: String,
+    data: Vec<u8>,
+}
+
+/// Builds an `ArchiveFile` (ph3) archive, mirroring how `extract_ph3` reads one.
+pub struct BuilderPh3<W: Write> {
+    writer: W,
+    entries: Vec<EntryPh3>,
+}
+impl<W: Write> BuilderPh3<W> {
+    pub fn new(writer: W) -> BuilderPh3<W> {
+        BuilderPh3 { writer, entries: Vec::new() }
+    }
+
+    pub fn append_file(
+        &mut self, dir_name: impl Into<String>, entry_name: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let data = read_file(path.as_ref())?;
+        self.entries.push(EntryPh3 { dir_name: dir_name.into(), entry_name: entry_name.into(), data });
+        Ok(())
+    }
+    pub fn append_dir_all(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        for (rel, path) in collect_files(dir.as_ref())? {
+            let (dir_name, entry_name) = split_rel_path(&rel);
+            self.append_file(dir_name, entry_name, path)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        struct Prepared {
+            record: Vec<u8>,
+            body: Vec<u8>,
+        }
+
+        let mut prepared = Vec::with_capacity(self.entries.len());
+        for entry in self.entries.drain(..) {
+            let compressed = try_compress(&entry.data)?;
+            let data_len = entry.data.len() as u32;
+            let (is_compressed, compressed_len, body) = match compressed {
+                Some(compressed) => {
+                    let len = compressed.len();
+                    (1u32, len as u32, compressed)
+                }
+                None => (0u32, data_len, entry.data),
+            };
+
+            let mut record = Vec::new();
+            write_wchar_str(&mut record, &entry.dir_name)?;
+            write_wchar_str(&mut record, &entry.entry_name)?;
+            record.write_u32::<LE>(is_compressed)?;
+            record.write_u32::<LE>(data_len)?;
+            record.write_u32::<LE>(compressed_len)?;
+            record.write_u32::<LE>(0)?; // offset, filled in below
+
+            let mut full_record = Vec::with_capacity(4 + record.len());
+            full_record.write_u32::<LE>(record.len() as u32)?;
+            full_record.extend_from_slice(&record);
+
+            prepared.push(Prepared { record: full_record, body });
+        }
+
+        // The offset field lives at a fixed position at the end of each record; patch it in
+        // once the position of each entry's contents is known. The header itself is written
+        // uncompressed, since compressing it would change its own size and invalidate the
+        // offsets it contains.
+        let mut header_blob = Vec::new();
+        let mut record_offsets = Vec::with_capacity(prepared.len());
+        for entry in &prepared {
+            record_offsets.push(header_blob.len());
+            header_blob.extend_from_slice(&entry.record);
+        }
+
+        let prefix_len = ARCHIVE_PH3_MAGIC.len() as u64 + 4 + 1 + 4 + header_blob.len() as u64;
+        let mut offset = prefix_len;
+        for (entry, &record_start) in prepared.iter().zip(&record_offsets) {
+            let offset_pos = record_start + entry.record.len() - 4;
+            (&mut header_blob[offset_pos..offset_pos + 4]).write_u32::<LE>(offset as u32)?;
+            offset += entry.body.len() as u64;
+        }
+
+        self.writer.write_all(ARCHIVE_PH3_MAGIC)?;
+        self.writer.write_u32::<LE>(prepared.len() as u32)?;
+        self.writer.write_u8(0)?; // header is stored uncompressed
+        self.writer.write_u32::<LE>(header_blob.len() as u32)?;
+        self.writer.write_all(&header_blob)?;
+        for entry in &prepared {
+            self.writer.write_all(&entry.body)?;
+        }
+
+        Ok(self.writer)
+    }
+}
+
+pub fn create_012m(dir: impl AsRef<Path>, writer: impl Write) -> Result<()> {
+    let mut builder = Builder012m::new(writer);
+    builder.append_dir_all(dir)?;
+    builder.finish()?;
+    Ok(())
+}
+pub fn create_ph3(dir: impl AsRef<Path>, writer: impl Write) -> Result<()> {
+    let mut builder = BuilderPh3::new(writer);
+    builder.append_dir_all(dir)?;
+    builder.finish()?;
+    Ok(())
+}